@@ -1,21 +1,34 @@
 use std::{
+    cell::RefCell,
     collections::HashMap,
+    convert::Infallible,
+    rc::Rc,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use axum::{
     async_trait,
-    extract::{FromRequestParts, Path, State},
-    http::{request::Parts, StatusCode},
-    response::IntoResponse,
+    body::{boxed, Full},
+    extract::{FromRequestParts, Path, Query, State},
+    http::{request::Parts, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing::get,
-    Router,
+    Json, Router,
 };
+use base64::Engine;
 use deno_core::{
     error::{AnyError, JsError},
     op, serde_json, serde_v8, v8, JsRuntime, OpState, RuntimeOptions,
 };
+use futures::stream::{self, Stream, StreamExt};
 use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::LocalSet};
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use tracing::{error, info};
 use tracing_subscriber::prelude::*;
 
@@ -30,24 +43,25 @@ async fn handle_fn_submit(
     body: String,
 ) -> Result<(), AppError> {
     let db_file = format!("{name}.db");
-    let db = Connection::open(&db_file)?;
+    let store = open_kv_store(&db_file)?;
 
-    db.execute("create table if not exists kv (key unique, value)", [])?;
-
-    state
-        .lock()?
-        .insert(name.clone(), (body, Arc::new(Mutex::new(db))));
+    state.lock()?.insert(name.clone(), (body, store));
 
     info!("added new function: {name}");
 
     Ok(())
 }
 
-// HTTP GET /fn/:name        curl localhost:8080/fn/hello
+// HTTP GET /fn/:name        curl -X GET -d '{}' localhost:8080/fn/hello
+// (a POST to the same path stores a new function body via handle_fn_submit
+// instead of invoking the function)
 async fn handle_fn_execute(
     State(state): State<AppState>,
     FunctionName(name): FunctionName,
-) -> Result<String, AppError> {
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, AppError> {
     let (fn_body, db) = state
         .lock()?
         .get(&name)
@@ -56,57 +70,522 @@ async fn handle_fn_execute(
 
     info!("invoking stored fn: {}", &name);
 
-    run_js(&name, &fn_body, db)
+    let request = RequestInfo::new(query, &headers, body);
+
+    let (log_tx, mut log_rx) = mpsc::unbounded_channel();
+    let logs = tokio::spawn(async move {
+        let mut capture = LogCapture::default();
+        while let Some(line) = log_rx.recv().await {
+            capture.push(line);
+        }
+        capture.into_lines()
+    });
+
+    let result = run_js(name, fn_body, db, log_tx, request).await?;
+    let logs = logs.await.unwrap_or_default();
+
+    Ok(fn_result_into_response(result, logs))
+}
+
+#[derive(Serialize)]
+struct FnExecuteResponse {
+    result: serde_json::Value,
+    logs: Vec<String>,
+}
+
+/// A function opts into shaping the HTTP response directly (as opposed to
+/// the plain `{ result, logs }` body used by every other invocation) by
+/// setting this field to `true` alongside `status`/`headers`/`body`. Gating
+/// on an explicit marker, rather than the mere presence of `status`, means a
+/// function that legitimately returns data with a `status` field isn't
+/// silently reinterpreted as a raw response.
+const HTTP_RESPONSE_MARKER: &str = "__httpResponse";
+
+/// Captured logs are still worth having even when a function opts into
+/// shaping its own response, so they're forwarded as this header instead of
+/// being dropped; base64-encoded since log lines aren't guaranteed to be
+/// valid header-value bytes.
+const LOGS_HEADER: &str = "x-fn-logs-base64";
+
+fn fn_result_into_response(result: serde_json::Value, logs: Vec<String>) -> Response {
+    let Some(shaped) = result
+        .as_object()
+        .filter(|obj| obj.get(HTTP_RESPONSE_MARKER) == Some(&serde_json::Value::Bool(true)))
+    else {
+        return Json(FnExecuteResponse { result, logs }).into_response();
+    };
+
+    let status = shaped
+        .get("status")
+        .and_then(serde_json::Value::as_u64)
+        .and_then(|code| u16::try_from(code).ok())
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .unwrap_or(StatusCode::OK);
+
+    let mut builder = Response::builder().status(status);
+    if let Some(response_headers) = shaped.get("headers").and_then(serde_json::Value::as_object) {
+        for (key, value) in response_headers {
+            if let Some(value) = value.as_str() {
+                builder = builder.header(key, value);
+            }
+        }
+    }
+
+    let logs_header = base64::engine::general_purpose::STANDARD
+        .encode(serde_json::to_string(&logs).unwrap_or_default());
+    builder = builder.header(LOGS_HEADER, logs_header);
+
+    let body = shaped.get("body").cloned().unwrap_or(serde_json::Value::Null);
+    let body = match body {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    };
+
+    builder
+        .body(boxed(Full::from(body)))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// Individual lines are capped to `MAX_LOG_LINE_BYTES` and truncated with a
+/// marker, and only `MAX_LOG_LINES` lines are kept in total, so a runaway
+/// function can't exhaust memory via logging.
+const MAX_LOG_LINE_BYTES: usize = 32 * 1024;
+const MAX_LOG_LINES: usize = 256;
+
+#[derive(Default)]
+struct LogCapture {
+    lines: Vec<String>,
+    dropped: bool,
+}
+
+impl LogCapture {
+    fn push(&mut self, mut line: String) {
+        if self.lines.len() >= MAX_LOG_LINES {
+            self.dropped = true;
+            return;
+        }
+
+        if line.len() > MAX_LOG_LINE_BYTES {
+            let mut cut = MAX_LOG_LINE_BYTES;
+            while !line.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            line.truncate(cut);
+            line.push_str(" ... [line truncated]");
+        }
+
+        self.lines.push(line);
+    }
+
+    fn into_lines(mut self) -> Vec<String> {
+        if self.dropped {
+            self.lines.push("... log output truncated".to_string());
+        }
+
+        self.lines
+    }
+}
+
+// HTTP GET /fn/:name/stream        curl -N localhost:8080/fn/hello/stream
+async fn handle_fn_stream(
+    State(state): State<AppState>,
+    FunctionName(name): FunctionName,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let (fn_body, db) = state
+        .lock()?
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| AppError::UnknownFunction(name.clone()))?;
+
+    info!("streaming invocation of stored fn: {}", &name);
+
+    let request = RequestInfo::new(query, &headers, body);
+
+    let (log_tx, log_rx) = mpsc::unbounded_channel();
+    let result = tokio::spawn(run_js(name, fn_body, db, log_tx, request));
+
+    let logs = UnboundedReceiverStream::new(log_rx).map(|line| Ok(Event::default().data(line)));
+
+    let outcome = stream::once(async move {
+        let event = match result.await {
+            Ok(Ok(value)) => Event::default().event("result").data(value.to_string()),
+            Ok(Err(err)) => Event::default().event("error").data(format!("{err:?}")),
+            Err(err) => Event::default()
+                .event("error")
+                .data(format!("js execution thread panicked: {err}")),
+        };
+
+        Ok(event)
+    });
+
+    Ok(Sse::new(logs.chain(outcome)).keep_alive(KeepAlive::default()))
 }
 
 #[op]
 fn op_log(state: &mut OpState, msg: String) {
     // emit the log message prefixed with the name of the function
-    info!("[{}]: {}", state.borrow::<String>(), msg);
+    let name = state.borrow::<String>().clone();
+    let tx = state.borrow::<mpsc::UnboundedSender<String>>();
+    let _ = tx.send(format!("[{name}]: {msg}"));
 }
 
 #[op]
-fn op_kv_set(state: &mut OpState, key: String, value: String) -> Result<(), AnyError> {
-    state
-        .borrow_mut::<DB>()
-        .lock()
-        // the error from a poisoned lock can't be sent between threads
-        // so we take it's msg contents and wrap them in an error that is Send
-        .map_err(|err| AnyError::msg(err.to_string()))?
-        .execute("replace into kv (key, value) values (?1, ?2)", [key, value])?;
+fn op_get_request(state: &mut OpState) -> RequestInfo {
+    state.borrow::<RequestInfo>().clone()
+}
 
-    Ok(())
+/// The caller's request, forwarded into the function as `globalThis.request`
+/// so a stored function can react to its caller instead of running fixed.
+#[derive(Clone, Serialize)]
+struct RequestInfo {
+    body: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+}
+
+impl RequestInfo {
+    /// Headers that don't make sense to forward into the function's sandbox
+    const EXCLUDED_HEADERS: &'static [&'static str] = &["host", "connection"];
+
+    fn new(query: HashMap<String, String>, headers: &HeaderMap, body: String) -> Self {
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| !Self::EXCLUDED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect();
+
+        Self {
+            body,
+            query,
+            headers,
+        }
+    }
+}
+
+#[op]
+async fn op_kv_set(
+    state: Rc<RefCell<OpState>>,
+    key: String,
+    value: String,
+) -> Result<(), AnyError> {
+    state.borrow().borrow::<DB>().set(&key, &value)
+}
+
+#[op]
+async fn op_kv_get(state: Rc<RefCell<OpState>>, key: String) -> Result<Option<String>, AnyError> {
+    state.borrow().borrow::<DB>().get(&key)
+}
+
+/// Storage backend for a function's key-value data, abstracted so ops don't
+/// care whether they're talking to SQLite, sled, or anything else.
+trait KvStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<String>, AnyError>;
+    fn set(&self, key: &str, value: &str) -> Result<(), AnyError>;
+}
+
+/// Default [`KvStore`], backed by the function's own SQLite database.
+/// Only compiled in when the `sled` feature isn't selecting [`SledKvStore`]
+/// instead, so exactly one backend is live (and neither trips `dead_code`).
+#[cfg(not(feature = "sled"))]
+struct SqliteKvStore {
+    conn: Mutex<Connection>,
+}
+
+#[cfg(not(feature = "sled"))]
+impl SqliteKvStore {
+    fn open(db_file: &str) -> Result<Self, AppError> {
+        let conn = Connection::open(db_file)?;
+        conn.execute("create table if not exists kv (key unique, value)", [])?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+#[cfg(not(feature = "sled"))]
+impl KvStore for SqliteKvStore {
+    fn get(&self, key: &str) -> Result<Option<String>, AnyError> {
+        let conn = self
+            .conn
+            .lock()
+            // the error from a poisoned lock can't be sent between threads
+            // so we take it's msg contents and wrap them in an error that is Send
+            .map_err(|err| AnyError::msg(err.to_string()))?;
+
+        let result = conn
+            .prepare("select value from kv where key = ?1")?
+            .query_row([key], |row| row.get(0))
+            .optional()?;
+
+        Ok(result)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), AnyError> {
+        self.conn
+            .lock()
+            .map_err(|err| AnyError::msg(err.to_string()))?
+            .execute(
+                "replace into kv (key, value) values (?1, ?2)",
+                [key, value],
+            )?;
+
+        Ok(())
+    }
+}
+
+/// Embedded, lock-free alternative to [`SqliteKvStore`], enabled with the
+/// `sled` feature. `rusqlite` stays a non-optional dependency, so it is still
+/// compiled and linked when this backend is selected.
+#[cfg(feature = "sled")]
+struct SledKvStore {
+    tree: sled::Db,
+}
+
+#[cfg(feature = "sled")]
+impl SledKvStore {
+    fn open(db_file: &str) -> Result<Self, AppError> {
+        let tree =
+            sled::open(db_file).map_err(|err| AppError::StorageError(err.to_string()))?;
+        Ok(Self { tree })
+    }
+}
+
+#[cfg(feature = "sled")]
+impl KvStore for SledKvStore {
+    fn get(&self, key: &str) -> Result<Option<String>, AnyError> {
+        let value = self.tree.get(key)?;
+        Ok(value.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), AnyError> {
+        self.tree.insert(key, value.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Open the configured [`KvStore`] backend for a function's database file.
+fn open_kv_store(db_file: &str) -> Result<Arc<dyn KvStore>, AppError> {
+    #[cfg(feature = "sled")]
+    {
+        Ok(Arc::new(SledKvStore::open(db_file)?))
+    }
+
+    #[cfg(not(feature = "sled"))]
+    {
+        Ok(Arc::new(SqliteKvStore::open(db_file)?))
+    }
 }
 
 #[op]
-fn op_kv_get(state: &mut OpState, key: String) -> Result<Option<String>, AnyError> {
-    let db = state
-        .borrow_mut::<DB>()
-        .lock()
-        // the error from a poisoned lock can't be sent between threads
-        // so we take it's msg contents and wrap them in an error that is Send
-        .map_err(|err| AnyError::msg(err.to_string()))?;
+async fn op_fetch(
+    state: Rc<RefCell<OpState>>,
+    url: String,
+    options: FetchOptions,
+) -> Result<FetchResponse, AnyError> {
+    let config = state.borrow().borrow::<FetchConfig>().clone();
+
+    let url = reqwest::Url::parse(&url)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| deno_core::error::type_error("fetch url has no host"))?;
+
+    if !config.allowed_hosts.iter().any(|allowed| allowed == host) {
+        return Err(deno_core::error::custom_error(
+            "PermissionDenied",
+            format!("host \"{host}\" is not in the fetch allow-list"),
+        ));
+    }
+
+    let method = options
+        .method
+        .as_deref()
+        .map(reqwest::Method::from_bytes)
+        .transpose()?
+        .unwrap_or(reqwest::Method::GET);
+
+    // an allowed host redirecting to an internal one must not bypass the
+    // allow-list, so don't follow redirects at all
+    let client = reqwest::Client::builder()
+        .timeout(config.timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let mut request = client.request(method, url);
+    for (key, value) in options.headers {
+        request = request.header(key, value);
+    }
+    if let Some(body) = options.body {
+        request = request.body(body);
+    }
+
+    let response = request.send().await?;
+
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                value.to_str().unwrap_or_default().to_owned(),
+            )
+        })
+        .collect();
+    let body = response.text().await?;
+
+    Ok(FetchResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[derive(Deserialize, Default)]
+struct FetchOptions {
+    method: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<String>,
+}
 
-    let result = db
-        .prepare("select value from kv where key = ?1")?
-        .query_row([key], |row| row.get(0))
-        .optional()?;
+#[derive(Serialize)]
+struct FetchResponse {
+    status: u16,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+/// Hosts `fetch` is allowed to reach and the timeout applied to each request,
+/// so a submitted function can't hammer arbitrary internal endpoints.
+#[derive(Clone)]
+struct FetchConfig {
+    allowed_hosts: Vec<String>,
+    timeout: Duration,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            // deny all hosts until an operator opts specific ones in
+            allowed_hosts: Vec::new(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
 
-    Ok(result)
+impl FetchConfig {
+    /// Build the operator-configured allow-list from the environment:
+    /// `FETCH_ALLOWED_HOSTS` is a comma-separated list of hostnames `fetch`
+    /// may reach, and `FETCH_TIMEOUT_SECS` overrides the per-request timeout.
+    /// Falls back to the deny-all [`Default`] for whichever is unset.
+    fn from_env() -> Self {
+        let allowed_hosts = std::env::var("FETCH_ALLOWED_HOSTS")
+            .ok()
+            .map(|hosts| {
+                hosts
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|host| !host.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let timeout = std::env::var("FETCH_TIMEOUT_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_else(|| Self::default().timeout);
+
+        Self {
+            allowed_hosts,
+            timeout,
+        }
+    }
 }
 
 const RUNTIME_BOOTSTRAP: &str = r#"
 globalThis.console = {
     log: (...args) => Deno.core.opSync("op_log", args.join(", "))
 }
-globalThis.set = (key, value) => (Deno.core.opSync("op_kv_set", key, JSON.stringify(value)), value)
-globalThis.get = (key) => JSON.parse(Deno.core.opSync("op_kv_get", key))
+globalThis.set = (key, value) => Deno.core.opAsync("op_kv_set", key, JSON.stringify(value)).then(() => value)
+globalThis.get = (key) => Deno.core.opAsync("op_kv_get", key).then((value) => JSON.parse(value))
+globalThis.fetch = (url, options = {}) => Deno.core.opAsync("op_fetch", url, {
+    method: options.method ?? "GET",
+    headers: options.headers ?? {},
+    body: options.body ?? null,
+}).then((response) => ({
+    status: response.status,
+    headers: response.headers,
+    body: response.body,
+}))
+Object.defineProperty(globalThis, "request", {
+    get: () => Deno.core.opSync("op_get_request"),
+})
 "#;
 
-fn run_js(name: &str, body: &str, db: DB) -> Result<String, AppError> {
+/// Run a stored function's body to completion, including any awaited async
+/// ops and a returned (but not yet settled) promise.
+///
+/// `JsRuntime` is `!Send`, so it can't be held across the `.await` points of
+/// an axum handler. Each invocation instead gets its own OS thread running a
+/// current-thread Tokio runtime and `LocalSet`, which is where the runtime
+/// actually lives and executes; the result is handed back over a oneshot
+/// channel.
+async fn run_js(
+    name: String,
+    body: String,
+    db: DB,
+    log_tx: mpsc::UnboundedSender<String>,
+    request: RequestInfo,
+) -> Result<serde_json::Value, AppError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    std::thread::spawn(move || {
+        let local = LocalSet::new();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build local js runtime");
+
+        let result =
+            runtime.block_on(local.run_until(execute_fn(name, body, db, log_tx, request)));
+
+        // the receiver is only dropped if the request was cancelled
+        let _ = tx.send(result);
+    });
+
+    rx.await.map_err(|_| AppError::DenoError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        message: "js execution thread panicked".into(),
+    })?
+}
+
+/// Baseline wall-clock budget given to a single invocation. Enforced twice:
+/// by the isolate watchdog below (for JS that's actually running, e.g.
+/// `while (true) {}`) and by wrapping the resolve loop in a timeout (for a
+/// promise that never settles, which leaves no running JS to terminate).
+const EXECUTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+async fn execute_fn(
+    name: String,
+    body: String,
+    db: DB,
+    log_tx: mpsc::UnboundedSender<String>,
+    request: RequestInfo,
+) -> Result<serde_json::Value, AppError> {
     let mut runtime = JsRuntime::new(RuntimeOptions {
         extensions: vec![deno_core::Extension::builder()
-            .ops(vec![op_log::decl(), op_kv_set::decl(), op_kv_get::decl()])
+            .ops(vec![
+                op_log::decl(),
+                op_get_request::decl(),
+                op_kv_set::decl(),
+                op_kv_get::decl(),
+                op_fetch::decl(),
+            ])
             .js(vec![("[runtime]", RUNTIME_BOOTSTRAP)])
             .build()],
         ..Default::default()
@@ -115,23 +594,114 @@ fn run_js(name: &str, body: &str, db: DB) -> Result<String, AppError> {
     let state = runtime.op_state();
 
     // inject the name of the function and access to the DB so ops have access
-    state.borrow_mut().put::<String>(name.to_owned());
+    state.borrow_mut().put::<String>(name.clone());
     state.borrow_mut().put(db);
+    state.borrow_mut().put(FetchConfig::from_env());
+    state.borrow_mut().put(log_tx);
+    state.borrow_mut().put(request);
+
+    // watchdog: terminate the isolate if it's still running past the deadline.
+    // This runs on its own OS thread with a real (blocking) sleep rather than
+    // a tokio task, because a synchronous JS loop like `while (true) {}` never
+    // yields back to the current-thread runtime that's driving the isolate —
+    // a tokio::sleep scheduled there would simply never get polled.
+    let isolate_handle = runtime.v8_isolate().thread_safe_handle();
+    let watchdog_handle = isolate_handle.clone();
+    let (cancel_tx, cancel_rx) = std::sync::mpsc::channel::<()>();
+    std::thread::spawn(move || {
+        if cancel_rx.recv_timeout(EXECUTION_TIMEOUT).is_err() {
+            watchdog_handle.terminate_execution();
+        }
+    });
 
-    let last_value = runtime.execute_script(name, body)?;
+    // the isolate watchdog above only interrupts JS that's actually running;
+    // a function that returns or awaits a promise which never settles (e.g.
+    // `await new Promise(() => {})`) has no pending ops and no running JS for
+    // terminate_execution() to interrupt, so bound the resolve loop itself by
+    // the same deadline.
+    let outcome = tokio::time::timeout(
+        EXECUTION_TIMEOUT,
+        execute_script_and_resolve(&mut runtime, &name, &body),
+    )
+    .await;
+    let _ = cancel_tx.send(());
+
+    let resolved = match outcome {
+        Err(_elapsed) => {
+            isolate_handle.terminate_execution();
+            return Err(AppError::Timeout);
+        }
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            // termination surfaces as an ordinary execute/event-loop error, so
+            // tell it apart from an ordinary JS fault via the isolate's own flag
+            if isolate_handle.is_execution_terminating() {
+                isolate_handle.cancel_terminate_execution();
+                return Err(AppError::Timeout);
+            }
+
+            return Err(js_error_response(err, &body));
+        }
+    };
 
-    // parse out the last evaluated expression from the function execution
+    // parse out the resolved value from the function execution
     let scope = &mut runtime.handle_scope();
-    let local = v8::Local::new(scope, last_value);
+    let local = v8::Local::new(scope, resolved);
     let deserialized_value = serde_v8::from_v8::<serde_json::Value>(scope, local)?;
 
     info!("result from \"{name}\": {:#?}", deserialized_value);
 
-    Ok(deserialized_value.to_string())
+    Ok(deserialized_value)
+}
+
+async fn execute_script_and_resolve(
+    runtime: &mut JsRuntime,
+    name: &str,
+    body: &str,
+) -> Result<v8::Global<v8::Value>, AnyError> {
+    let last_value = runtime.execute_script(name, body)?;
+    resolve_value(runtime, last_value).await
+}
+
+/// If `value` is a promise, drive `runtime`'s event loop until it settles
+/// (if it hasn't already) and return its resolved (or rejected) value; any
+/// other value is returned unchanged.
+async fn resolve_value(
+    runtime: &mut JsRuntime,
+    value: v8::Global<v8::Value>,
+) -> Result<v8::Global<v8::Value>, AnyError> {
+    loop {
+        let state = {
+            let scope = &mut runtime.handle_scope();
+            let local = v8::Local::new(scope, value.clone());
+            match v8::Local::<v8::Promise>::try_from(local) {
+                Ok(promise) => promise.state(),
+                Err(_) => return Ok(value),
+            }
+        };
+
+        match state {
+            v8::PromiseState::Pending => {
+                runtime.run_event_loop(false).await?;
+                // with no pending ops, run_event_loop returns immediately
+                // without actually suspending, so a promise that never
+                // settles (e.g. `new Promise(() => {})`) would otherwise spin
+                // this task forever without yielding back to the scheduler
+                tokio::task::yield_now().await;
+            }
+            _settled => {
+                let scope = &mut runtime.handle_scope();
+                let local = v8::Local::new(scope, value.clone());
+                let promise = v8::Local::<v8::Promise>::try_from(local).unwrap();
+                let result = promise.result(scope);
+                return Ok(v8::Global::new(scope, result));
+            }
+        }
+    }
 }
 
-/// Threadsafe lock around a sqlite database connection
-type DB = Arc<Mutex<Connection>>;
+/// Storage-agnostic handle to a function's key-value store
+type DB = Arc<dyn KvStore>;
 /// Threadsafe lock around a map of function name -> body & db connection
 type AppState = Arc<Mutex<HashMap<String, (String, DB)>>>;
 
@@ -143,7 +713,8 @@ async fn main() {
 
     let app = Router::with_state(state)
         .route("/", get(handle_root))
-        .route("/fn/:name", get(handle_fn_execute).post(handle_fn_submit));
+        .route("/fn/:name", get(handle_fn_execute).post(handle_fn_submit))
+        .route("/fn/:name/stream", get(handle_fn_stream));
 
     let addr = std::net::SocketAddr::from((std::net::Ipv4Addr::UNSPECIFIED, 8080));
     info!("listening on {}", addr);
@@ -179,23 +750,37 @@ fn register_trace_stdout_listener() {
 /// Implements From for various error types, and IntoResponse to build an HTTP response
 #[derive(Debug)]
 enum AppError {
-    SqliteError(String),
+    StorageError(String),
     LockPoisoned(String),
     UnknownFunction(String),
-    JsError(JsError),
-    DenoError(String),
+    JsError(JsErrorDetails),
+    DenoError { status: StatusCode, message: String },
     V8SerialisationError(String),
+    Timeout,
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         match self {
-            AppError::JsError(js_error) => {
-                format!("error evaluating function: {js_error}").into_response()
+            AppError::JsError(details) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, Json(details)).into_response()
             }
             AppError::UnknownFunction(e) => {
                 (StatusCode::BAD_REQUEST, format!("unknown function: {e}")).into_response()
             }
+            AppError::Timeout => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "function exceeded its execution timeout",
+            )
+                .into_response(),
+            AppError::DenoError { status, message } => {
+                if status.is_server_error() {
+                    error!("internal error: {message}");
+                    (status, "internal server error").into_response()
+                } else {
+                    (status, message).into_response()
+                }
+            }
             err => {
                 error!("internal error: {err:?}");
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response()
@@ -206,7 +791,7 @@ impl IntoResponse for AppError {
 
 impl From<rusqlite::Error> for AppError {
     fn from(err: rusqlite::Error) -> Self {
-        AppError::SqliteError(err.to_string())
+        AppError::StorageError(err.to_string())
     }
 }
 
@@ -216,21 +801,121 @@ impl<T> From<std::sync::PoisonError<T>> for AppError {
     }
 }
 
-impl From<deno_core::anyhow::Error> for AppError {
-    fn from(err: deno_core::anyhow::Error) -> Self {
-        match err.downcast::<JsError>() {
-            Ok(js_error) => AppError::JsError(js_error),
-            Err(err) => AppError::DenoError(err.to_string()),
+impl From<serde_v8::Error> for AppError {
+    fn from(err: serde_v8::Error) -> Self {
+        AppError::V8SerialisationError(err.to_string())
+    }
+}
+
+/// Turn an error surfaced while evaluating a function into an [`AppError`],
+/// classifying user code faults (a [`JsError`]) separately from internal
+/// runtime faults, and further classifying the latter by their deno_core
+/// error class so they get the right HTTP status in [`IntoResponse`].
+fn js_error_response(err: AnyError, source: &str) -> AppError {
+    match err.downcast::<JsError>() {
+        Ok(js_error) => AppError::JsError(JsErrorDetails::from_js_error(js_error, source)),
+        Err(err) => AppError::DenoError {
+            status: deno_error_status(&err),
+            message: err.to_string(),
+        },
+    }
+}
+
+/// Map a deno_core error's class (as set by [`deno_core::error::custom_error`]
+/// / [`deno_core::error::type_error`]) to the HTTP status it should surface
+/// as; anything uncategorised is treated as an internal fault.
+fn deno_error_status(err: &AnyError) -> StatusCode {
+    match deno_core::error::get_custom_error_class(err) {
+        Some("TypeError") => StatusCode::BAD_REQUEST,
+        Some("PermissionDenied") => StatusCode::FORBIDDEN,
+        Some("NotFound") => StatusCode::NOT_FOUND,
+        Some("TimedOut") => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// A [`JsError`] reshaped for the HTTP response: the exception message plus
+/// its stack frames, with source-mapped positions substituted in wherever
+/// the function body carries an inline `//# sourceMappingURL=data:...` map.
+#[derive(Debug, Serialize)]
+struct JsErrorDetails {
+    message: String,
+    frames: Vec<JsErrorFrame>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsErrorFrame {
+    file_name: Option<String>,
+    function_name: Option<String>,
+    line_number: Option<i64>,
+    column_number: Option<i64>,
+}
+
+impl JsErrorDetails {
+    fn from_js_error(js_error: JsError, source: &str) -> Self {
+        let source_map = inline_source_map(source);
+
+        let frames = js_error
+            .frames
+            .into_iter()
+            .map(|frame| {
+                let mut frame = JsErrorFrame {
+                    file_name: frame.file_name,
+                    function_name: frame.function_name,
+                    line_number: frame.line_number,
+                    column_number: frame.column_number,
+                };
+
+                if let Some(map) = &source_map {
+                    frame.apply_source_map(map);
+                }
+
+                frame
+            })
+            .collect();
+
+        Self {
+            message: js_error.exception_message,
+            frames,
         }
     }
 }
 
-impl From<serde_v8::Error> for AppError {
-    fn from(err: serde_v8::Error) -> Self {
-        AppError::V8SerialisationError(err.to_string())
+impl JsErrorFrame {
+    fn apply_source_map(&mut self, map: &sourcemap::SourceMap) {
+        let (Some(line), Some(column)) = (self.line_number, self.column_number) else {
+            return;
+        };
+
+        // deno/v8 report 1-based lines and columns, source maps are 0-based
+        let Some(token) =
+            map.lookup_token((line - 1).max(0) as u32, (column - 1).max(0) as u32)
+        else {
+            return;
+        };
+
+        self.line_number = Some(token.get_src_line() as i64 + 1);
+        self.column_number = Some(token.get_src_col() as i64 + 1);
+        if let Some(source) = token.get_source() {
+            self.file_name = Some(source.to_owned());
+        }
     }
 }
 
+/// Decode an inline `//# sourceMappingURL=data:application/json;base64,...`
+/// comment out of a function body, if it carries one.
+fn inline_source_map(body: &str) -> Option<sourcemap::SourceMap> {
+    let marker = "//# sourceMappingURL=";
+    let line = body.lines().rfind(|line| line.starts_with(marker))?;
+    let (_, base64_data) = line[marker.len()..].split_once("base64,")?;
+
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(base64_data.trim())
+        .ok()?;
+
+    sourcemap::SourceMap::from_slice(&decoded).ok()
+}
+
 /// Extractor that also validates a function name from the URL
 struct FunctionName(String);
 